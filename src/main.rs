@@ -5,6 +5,9 @@ use std::io::prelude::*;
 // For overloading operators
 use std::ops;
 
+// Shared read-only scene data across the thread pool
+use std::sync::Arc;
+
 // Random number generation
 use rand::prelude::*;
 
@@ -18,18 +21,29 @@ use std::sync::mpsc::channel;
 // Ray-tracing properties
 const WIDTH: i32 = 512;
 const SAMPLES: i32 = 256;
-const GAIN: f32 = 224.0 / SAMPLES as f32;
+
+// Default tone-mapping parameters for the output stage (overridable via
+// --exposure / --gamma); see `V::to_srgb_bytes`.
+const DEFAULT_EXPOSURE: f32 = 1.0;
+const DEFAULT_GAMMA: f32 = 2.2;
+
+// Transmission through dielectrics plus the existing mirror reflection can
+// recurse forever, so bounces are capped at this depth.
+const MAX_BOUNCES: i32 = 10;
 
 //Define a vector struct with overloaded operators
 #[derive(Debug, Copy, Clone)]
 struct V { x: f32, y: f32, z: f32 }
 
-// Return as clamped byte-array representing RGB color
 impl V {
-    fn c(self) -> [u8; 3] {
-        [self.x.clamp(0.0, 255.0) as u8,
-         self.y.clamp(0.0, 255.0) as u8,
-         self.z.clamp(0.0, 255.0) as u8]
+    // Tone-map this (HDR, unclamped) linear radiance into displayable sRGB
+    // bytes. `exposure` compresses the highlights via `1 - exp(-c*exposure)`
+    // instead of a straight clamp, and the result is gamma-encoded with
+    // `c'^(1/gamma)` before scaling to 0..255.
+    fn to_srgb_bytes(self, exposure: f32, gamma: f32) -> [u8; 3] {
+        let tonemap = |c: f32| ((1.0 - (-c * exposure).exp()).max(0.0).powf(1.0 / gamma) * 255.0)
+            .clamp(0.0, 255.0) as u8;
+        [tonemap(self.x), tonemap(self.y), tonemap(self.z)]
     }
 }
 
@@ -68,108 +82,333 @@ fn r() -> f32 {
     rng.gen()
 }
 
-// The intersection test for ray (o = origin, d = direction).
-// - Return 2 if a sphere hit was found (and also return distance t and normal n).
-// - Return 0 if no sphere hit was found but ray goes upward (t and n are meaningless)
-// - Return 1 if no sphere hit was found but ray goes downward (t and n are for ground plane intersection)
-fn trace(o: &V, d: &V) -> (i32, f32, V) {
-    // The world is encoded in g, with rows (numbers) each with 9-bits of info (1 = sphere, 0 = nothing)
-    /* Original says 'aek'
-      let g[]={247570,280596,280600,249748,18578,18577,231184,16,16};
-
-        16                    1
-        16                    1
-        231184   111    111   1
-        18577       1  1   1  1   1
-        18578       1  1   1  1  1
-        249748   1111  11111  1 1
-        280600  1   1  1      11
-        280596  1   1  1      1 1
-        247570   1111   111   1  1
-    */
-
-    let g = [202766, 202779, 6150, 6152, 7579, 5902];
-    /* a '.ru' version (traces in about 6 seconds in release mode)
-     * 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 // 0
-     * 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 // 0
-     * 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 // 0
-     * 0 0 0 0 0 0 1 0 1 1 1 0 0 0 0 1 1 1 0 // 5902
-     * 0 0 0 0 0 0 1 1 1 0 1 1 0 0 1 1 0 1 1 // 7579
-     * 0 0 0 0 0 0 1 1 0 0 0 0 0 0 0 1 0 0 0 // 6152
-     * 0 0 0 0 0 0 1 1 0 0 0 0 0 0 0 0 1 1 0 // 6150
-     * 0 1 1 0 0 0 1 1 0 0 0 0 0 0 1 1 0 1 1 // 202779
-     * 0 1 1 0 0 0 1 1 0 0 0 0 0 0 0 1 1 1 0 // 202766
-     */
+// Linear interpolation between two vectors, used to evaluate a moving
+// sphere's center at a given point in the shutter interval.
+fn lerp(a: V, b: V, t: f32) -> V { a + (b + a * -1.0) * t }
+
+// A sphere primitive, as read from a scene file. Spheres carry their own
+// center and radius (rather than sitting as implicit unit spheres on a
+// grid) plus the material properties used while shading a hit.
+#[derive(Debug, Copy, Clone)]
+struct Sphere {
+    center: V,
+    radius: f32,
+    color: V,
+    shininess: f32,
+    reflectivity: f32,
+    // Dielectrics (glass) refract instead of shading diffuse/specular; `ior`
+    // is only meaningful when `dielectric` is set.
+    dielectric: bool,
+    ior: f32,
+    // Radiance this sphere emits on its own; non-zero turns it into an area
+    // light that path-traced rays can hit directly.
+    emission: V,
+    // The sphere's center at the end of the shutter interval (time = 1),
+    // for motion blur; equal to `center` for a static sphere.
+    center1: V,
+}
+
+impl Sphere {
+    // The sphere's center at a given point in [0, 1) of the shutter interval
+    fn center_at(&self, time: f32) -> V { lerp(self.center, self.center1, time) }
+}
+
+// Camera parameters, as read from the scene file's `c` record.
+#[derive(Debug, Copy, Clone)]
+struct Camera {
+    pos: V,
+    fov: f32,
+    look_at: V,
+}
+
+// An equirectangular environment map, used for image-based lighting when a
+// ray escapes to the sky. Loaded from a P6 PPM, same as the renderer's
+// output format.
+#[derive(Debug, Clone)]
+struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<V>,
+}
+
+// A fully parsed scene: geometry, lights, camera, and whether the ground
+// plane is present.
+#[derive(Debug, Clone)]
+struct Scene {
+    spheres: Vec<Sphere>,
+    lights: Vec<V>,
+    camera: Camera,
+    has_floor: bool,
+    // Selects the unbiased Monte Carlo path tracer over the default
+    // Whitted-style tracer; set from a CLI flag, not the scene file.
+    path_trace: bool,
+    // Image-based lighting for the sky; falls back to the analytic gradient
+    // when absent. Set from a CLI flag, not the scene file.
+    env: Option<EnvironmentMap>,
+}
+
+// Parse a scene description in the classic c-ray line-record format:
+//   s x y z rad r g b shininess reflectivity [ior]   (sphere, may repeat)
+//   e r g b                                           (make the previous sphere emissive)
+//   m x y z                                           (give the previous sphere a motion end-position)
+//   l x y z                                           (point light, may repeat)
+//   c x y z fov tx ty tz                               (camera: pos, fov, look-at)
+//   p                                                  (ground plane at z = 0)
+// A trailing `ior` on a sphere record marks it as a dielectric (glass) with
+// that index of refraction; without it, the sphere is opaque. `e` and `m`
+// records apply to whichever `s` record precedes them: `e` is only useful in
+// path-traced mode, where emissive spheres act as area lights; `m` gives a
+// sphere a center position at the end of the shutter interval (time = 1) so
+// it motion-blurs between `center` and that position over a frame.
+// Blank lines and lines starting with '#' are ignored.
+fn load_scene(path: &str) -> Scene {
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read scene file '{}': {}", path, e));
+
+    let mut spheres = Vec::new();
+    let mut lights = Vec::new();
+    let mut camera = None;
+    let mut has_floor = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
 
+        let mut fields = line.split_whitespace();
+        let kind = fields.next().unwrap();
+        let mut num = || -> f32 {
+            fields.next()
+                .unwrap_or_else(|| panic!("truncated '{}' record in scene file", kind))
+                .parse()
+                .unwrap_or_else(|_| panic!("bad number in '{}' record in scene file", kind))
+        };
+
+        match kind {
+            "s" => {
+                let center = V{x: num(), y: num(), z: num()};
+                let radius = num();
+                let color = V{x: num(), y: num(), z: num()};
+                let shininess = num();
+                let reflectivity = num();
+                let ior = fields.next().map(|tok| tok.parse::<f32>()
+                    .unwrap_or_else(|_| panic!("bad ior in 's' record in scene file")));
+
+                spheres.push(Sphere {
+                    center, radius, color, shininess, reflectivity,
+                    dielectric: ior.is_some(),
+                    ior: ior.unwrap_or(1.0),
+                    emission: V{x: 0.0, y: 0.0, z: 0.0},
+                    center1: center,
+                });
+            },
+            "e" => spheres.last_mut()
+                .unwrap_or_else(|| panic!("'e' record with no preceding 's' record in scene file"))
+                .emission = V{x: num(), y: num(), z: num()},
+            "m" => spheres.last_mut()
+                .unwrap_or_else(|| panic!("'m' record with no preceding 's' record in scene file"))
+                .center1 = V{x: num(), y: num(), z: num()},
+            "l" => lights.push(V{x: num(), y: num(), z: num()}),
+            "c" => camera = Some(Camera {
+                pos: V{x: num(), y: num(), z: num()},
+                fov: num(),
+                look_at: V{x: num(), y: num(), z: num()},
+            }),
+            "p" => has_floor = true,
+            other => panic!("unknown scene record '{}' in '{}'", other, path),
+        }
+    }
+
+    Scene {
+        spheres,
+        lights,
+        camera: camera.unwrap_or_else(|| panic!("scene file '{}' has no 'c' camera record", path)),
+        has_floor,
+        path_trace: false,
+        env: None,
+    }
+}
+
+// Load an environment map from a P6 PPM file (same format the renderer
+// itself writes: `P6 width height maxval <raw RGB bytes>`).
+fn load_environment(path: &str) -> EnvironmentMap {
+    let data = std::fs::read(path)
+        .unwrap_or_else(|e| panic!("failed to read environment map '{}': {}", path, e));
+
+    let mut pos = 0;
+    let mut next_token = || -> &str {
+        while data[pos].is_ascii_whitespace() { pos += 1; }
+        let start = pos;
+        while !data[pos].is_ascii_whitespace() { pos += 1; }
+        std::str::from_utf8(&data[start..pos]).unwrap()
+    };
+
+    let magic = next_token();
+    if magic != "P6" {
+        panic!("environment map '{}' is not a P6 PPM file", path);
+    }
+    let width: usize = next_token().parse().expect("bad width in environment map header");
+    let height: usize = next_token().parse().expect("bad height in environment map header");
+    next_token(); // maxval, assumed to be 255
+    pos += 1; // the single whitespace byte separating header from pixel data
+
+    let pixels = data[pos..].chunks_exact(3)
+        .map(|p| V{x: p[0] as f32, y: p[1] as f32, z: p[2] as f32})
+        .collect();
+
+    EnvironmentMap { width, height, pixels }
+}
+
+// Map a normalized ray direction to equirectangular texture coordinates and
+// bilinearly sample the environment map (wrapping in u, clamping in v).
+fn sample_environment(env: &EnvironmentMap, d: &V) -> V {
+    let u = 0.5 + d.y.atan2(d.x) / (2.0 * std::f32::consts::PI);
+    let v = d.z.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+
+    let fx = u * env.width as f32 - 0.5;
+    let fy = v * env.height as f32 - 0.5;
+    let x0 = fx.floor() as i64;
+    let y0 = fy.floor() as i64;
+    let tx = fx - x0 as f32;
+    let ty = fy - y0 as f32;
+
+    let pixel = |x: i64, y: i64| -> V {
+        let wx = x.rem_euclid(env.width as i64) as usize;
+        let wy = y.clamp(0, env.height as i64 - 1) as usize;
+        env.pixels[wy * env.width + wx]
+    };
+
+    let top = pixel(x0, y0) * (1.0 - tx) + pixel(x0 + 1, y0) * tx;
+    let bottom = pixel(x0, y0 + 1) * (1.0 - tx) + pixel(x0 + 1, y0 + 1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+// The intersection test for ray (o = origin, d = direction), at the given
+// point `shutter_time` in [0, 1) of the camera's shutter interval (moving
+// spheres are evaluated at their center for that instant).
+// - Return 2 if a sphere hit was found (and also return distance t, normal n, and the sphere's index).
+// - Return 1 if no sphere hit was found but ray goes downward into the ground plane (t and n are for that hit)
+// - Return 0 if nothing was hit (t, n, and the index are meaningless)
+fn trace(scene: &Scene, o: &V, d: &V, shutter_time: f32) -> (i32, f32, V, usize) {
     // Initialize to max time and pointing upward
     let mut t = f32::MAX;
     let mut m = 0;
     let mut n = V{x:0.0, y:0.0, z:1.0};
+    let mut hit = 0;
 
     // Check if intersects with floor plane
-    let p = -o.z/d.z;
-    if p > 0.01 {
-        t = p;
-        m = 1;
+    if scene.has_floor {
+        let p = -o.z/d.z;
+        if p > 0.01 {
+            t = p;
+            m = 1;
+        }
     }
 
-    // Loop over all spheres
-    for (k, j) in iproduct!(0..19, 0..g.len()) {
-        if g[j] & 1<<k > 0 { //For this line j, is there a sphere at column k ?
-            // There is a sphere but does the ray hit it ?
-            let p = *o + V{x: -k as f32, y: 0.0, z: -(j as f32) - 4.0};
-            let b = p % *d;
-            let c = p % p - 1.0;
-            let q = b * b - c;
-
-            // Does the ray hit the sphere (solution to quadratic is non-imaginary)
-            if q > 0.0 {
-                // It does but is it closer than previous hit and in front of camera?
-                let s= -b - q.sqrt();
-                if s < t && s > 0.01 {
-                    t = s;
-                    n = !(p + *d * t);
-                    m = 2;
-                }
+    // Loop over all spheres in the scene
+    for (i, s) in scene.spheres.iter().enumerate() {
+        let center = s.center_at(shutter_time);
+        let p = *o + center * -1.0;
+        let b = p % *d;
+        let c = p % p - s.radius * s.radius;
+        let q = b * b - c;
+
+        // Does the ray hit the sphere (solution to quadratic is non-imaginary)
+        if q > 0.0 {
+            // It does but is it closer than previous hit and in front of camera?
+            let hit_t = -b - q.sqrt();
+            if hit_t < t && hit_t > 0.01 {
+                t = hit_t;
+                n = !(p + *d * t);
+                m = 2;
+                hit = i;
             }
         }
     }
 
-    // Return type of intersection, time, and normal
-    (m, t, n)
+    // Return type of intersection, time, normal, and (for spheres) which one
+    (m, t, n, hit)
 }
 
-// Sample the world and return the pixel color for a ray passing by point o (Origin) and d (Direction)
-fn sample(o: &V, d: &V) -> V {
+// Draw a cosine-weighted random direction over the hemisphere that `n`
+// points into, for Monte Carlo path tracing of diffuse surfaces.
+fn cosine_sample_hemisphere(n: &V) -> V {
+    // Build an orthonormal basis around n from any non-parallel helper vector
+    let helper = if n.x.abs() > 0.9 { V{x: 0.0, y: 1.0, z: 0.0} } else { V{x: 1.0, y: 0.0, z: 0.0} };
+    let tangent = !(helper ^ *n);
+    let bitangent = *n ^ tangent;
+
+    let u1 = r();
+    let u2 = r();
+    let phi = 2.0 * std::f32::consts::PI * u1;
+    let radius = u2.sqrt();
+
+    tangent * (phi.cos() * radius) + bitangent * (phi.sin() * radius) + *n * (1.0 - u2).sqrt()
+}
+
+// Monte Carlo path-tracing shading for a diffuse sphere hit: sample one
+// cosine-weighted bounce direction and recurse, weighting by the surface
+// albedo (the pi and cos(theta) terms cancel against the cosine-weighted
+// pdf, so no extra factor is needed). Russian roulette terminates the path
+// once it's gone deep enough to do so safely. Emissive spheres add their
+// own radiance, letting paths hit them directly as area lights.
+fn path_trace_sphere(scene: &Scene, s: &Sphere, h: &V, n: &V, depth: i32, shutter_time: f32) -> V {
+    let mut weight = 1.0;
+    if depth > 4 {
+        let p = s.color.x.max(s.color.y).max(s.color.z).max(0.05);
+        if r() > p {
+            return s.emission;
+        }
+        weight = 1.0 / p;
+    }
+
+    let bounce = cosine_sample_hemisphere(n);
+    let incoming = sample(scene, h, &bounce, depth + 1, shutter_time) * weight;
+    s.emission + V{x: s.color.x * incoming.x, y: s.color.y * incoming.y, z: s.color.z * incoming.z}
+}
+
+// Sample the world and return the pixel color for a ray passing by point o (Origin) and d (Direction),
+// at the given point `shutter_time` in the camera's shutter interval (for motion blur).
+// `depth` counts bounces so far; once it exceeds MAX_BOUNCES the recursion
+// (reflection, refraction) bottoms out to black instead of looping forever.
+fn sample(scene: &Scene, o: &V, d: &V, depth: i32, shutter_time: f32) -> V {
+    if depth > MAX_BOUNCES {
+        return V{x: 0.0, y: 0.0, z: 0.0};
+    }
+
     // Trace this ray through the world
-    let (m, t, n) = trace(o, d);
+    let (m, t, n, hit) = trace(scene, o, d, shutter_time);
 
     // Match based on type of hit
     match m {
-        // Sky color approaches black exponentially the steeper the ray angle
-        0 => V{x: 0.7, y:0.6, z: 1.0} * (1.0 - d.z).powi(4),
+        // Sky: sampled from the environment map if one was loaded, otherwise
+        // the analytic gradient that approaches black toward the horizon
+        0 => match &scene.env {
+            Some(env) => sample_environment(env, d),
+            None => V{x: 0.7, y:0.6, z: 1.0} * (1.0 - d.z).powi(4),
+        },
 
         // Hit sphere or plane
         _ => {
-            // Intersection point, light direction (jittered for soft shadows), and reflection direction
-            // Note: Light is a point light located at (9, 9, 16)
-            let mut h = *o + *d * t;
-            let l = !(V{x: 9.0 + r(), y: 9.0 + r(), z: 16.0} + h * -1.0);
+            let h = *o + *d * t;
             let rv = *d + n * (n % *d * -2.0);
 
-            // Calculated the lambertian diffuse component
-            let mut b = l % n;
-
-            // Trace shadow ray (can skip if lambertian is non-positive)
-            if b < 0.0 || trace(&h, &l).0 > 0 {
-                b = 0.0;
-            }
-
             match m {
-                // Hit ground plane
+                // Hit ground plane: lambertian checkerboard, lit by every light in the scene
                 1 => {
-                    h = h * 0.2;
-                    let check = if (h.x.ceil() + h.y.ceil()) as i32 & 1 == 1 {
+                    let mut b = 0.0;
+                    for light in &scene.lights {
+                        // Light position jittered for soft shadows
+                        let l = !(V{x: light.x + r(), y: light.y + r(), z: light.z} + h * -1.0);
+                        let bl = l % n;
+                        if bl > 0.0 && trace(scene, &h, &l, shutter_time).0 == 0 {
+                            b += bl;
+                        }
+                    }
+
+                    let hp = h * 0.2;
+                    let check = if (hp.x.ceil() + hp.y.ceil()) as i32 & 1 == 1 {
                         V{x: 3.0, y: 1.0, z: 1.0}
                     } else {
                         V{x: 3.0, y: 3.0, z: 3.0}
@@ -179,76 +418,177 @@ fn sample(o: &V, d: &V) -> V {
                     check * (b * 0.2 + 0.1)
                 },
 
+                // Hit a dielectric sphere: refract (or total-internally-reflect) and
+                // blend with the mirror reflection by Schlick's Fresnel approximation
+                2 if scene.spheres[hit].dielectric => {
+                    let ior = scene.spheres[hit].ior;
+
+                    // Figure out if the ray is entering or exiting the sphere from
+                    // the sign of n % d, flipping the normal and swapping ior/1/ior
+                    // to match whichever side we're on
+                    let mut n2 = n;
+                    let mut eta = 1.0 / ior;
+                    let mut cos_i = -(n % *d);
+                    if cos_i < 0.0 {
+                        n2 = n2 * -1.0;
+                        cos_i = -cos_i;
+                        eta = ior;
+                    }
+
+                    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+                    if sin2_t > 1.0 {
+                        // Total internal reflection: no transmitted ray
+                        sample(scene, &h, &rv, depth + 1, shutter_time)
+                    } else {
+                        let tv = *d * eta + n2 * (eta * cos_i - (1.0 - sin2_t).sqrt());
+
+                        let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+                        let fresnel = r0 + (1.0 - r0) * (1.0 - cos_i).powi(5);
+
+                        sample(scene, &h, &rv, depth + 1, shutter_time) * fresnel
+                            + sample(scene, &h, &tv, depth + 1, shutter_time) * (1.0 - fresnel)
+                    }
+                },
+
+                // Hit sphere in path-traced mode: cosine-weighted hemisphere bounce
+                _ if scene.path_trace => path_trace_sphere(scene, &scene.spheres[hit], &h, &n, depth, shutter_time),
+
                 // Hit sphere (do recursive bounce for reflectivity)
                 _ => {
-                    // Combine diffuse with Phong specular component
-                    let p = ((l % rv) * (if b > 0.0 { 1.0 } else { 0.0 })).powi(99);
-
-                    // Trace reflection ray and attenuate by 50% for lost light
-                    V{x: p, y: p, z: p} + sample(&h, &rv) * 0.5
+                    let s = &scene.spheres[hit];
+                    let mut diffuse = 0.0;
+                    let mut specular = 0.0;
+
+                    for light in &scene.lights {
+                        // Light position jittered for soft shadows
+                        let l = !(V{x: light.x + r(), y: light.y + r(), z: light.z} + h * -1.0);
+                        let b = l % n;
+
+                        // Skip this light if it's behind the surface or in shadow
+                        if b <= 0.0 || trace(scene, &h, &l, shutter_time).0 > 0 {
+                            continue;
+                        }
+
+                        diffuse += b;
+                        specular += (l % rv).max(0.0).powf(s.shininess);
+                    }
+
+                    // Combine the sphere's own diffuse color with the Phong specular
+                    // highlight, plus a reflection bounce attenuated by reflectivity
+                    s.color * (diffuse * 0.2 + 0.1)
+                        + V{x: specular, y: specular, z: specular}
+                        + sample(scene, &h, &rv, depth + 1, shutter_time) * s.reflectivity
                 }
             }
         }
     }
 }
 
-fn trace_pixel(x: i32, y: i32, av: V, bv: V, cv: V, i: usize) -> (usize, V) {
-    // Reuse the vector class to store not XYZ but a RGB pixel color
-    let mut p = V{x: 13.0, y: 13.0, z: 13.0};
-
-    // Cast SAMPLES rays per pixel (sub-pixel super sampling)
-    for _ in 0..SAMPLES {
-        // Ray origin random jitter
-        let t = av*(r() - 0.5) * 99.0 + bv*(r() - 0.5) * 99.0;
-
-        // Ray originates from (16, 16, 8) jittered by t
-        // Direction is also jittered (by same t) which gets you the distance-attenuated blur
-        p = sample(
-            &(V{x:16.0, y: 16.0, z: 8.0} + t),
-            &(!(t * -1.0 + (av*(r() + x as f32) + bv * (y as f32 + r()) + cv) * 16.0))
-        ) * GAIN + p; // +p for color accumulation, GAIN is just a brightness gain
-    }
-
-    // Return the color and index
-    (i, p)
+// Cast a single sub-pixel super-sampled ray for pixel (x, y) and return its
+// contribution. One pass casts this once per pixel; SAMPLES passes average
+// together into the final image.
+fn sample_pixel(scene: &Scene, x: i32, y: i32, av: V, bv: V, cv: V) -> V {
+    // Ray origin random jitter
+    let t = av*(r() - 0.5) * 99.0 + bv*(r() - 0.5) * 99.0;
+
+    // A random instant within the shutter interval for this sample; averaging
+    // many of these together is what produces the motion blur
+    let shutter_time = r();
+
+    // Ray originates from the camera position jittered by t
+    // Direction is also jittered (by same t) which gets you the distance-attenuated blur
+    sample(
+        scene,
+        &(scene.camera.pos + t),
+        &(!(t * -1.0 + (av*(r() + x as f32) + bv * (y as f32 + r()) + cv) * 16.0)),
+        0,
+        shutter_time
+    )
 }
 
 fn main() {
-    // Vectors for orienting camera
-    let gv = !V{x: -6.0, y: -16.0, z: 0.0};             // Camera direction
-    let av = !(V{x:0.0, y:0.0, z:1.0} ^ gv) * 0.002;    // Camera up vector, Z is pointing up
-    let bv = !(gv ^ av) * 0.002;                        // The right vector, obtained via traditional cross-product
+    // The scene file path is the one required CLI argument. Optional flags:
+    //   --path-trace       use the Monte Carlo path tracer instead of Whitted
+    //   --env <file>       light the sky from a P6 PPM environment map
+    //   --exposure <f32>   output exposure (default 1.0); see V::to_srgb_bytes
+    //   --gamma <f32>      output gamma (default 2.2); see V::to_srgb_bytes
+    let mut args = std::env::args().skip(1);
+    let scene_path = args.next()
+        .unwrap_or_else(|| panic!("usage: rust-biz-trace <scene-file> [--path-trace] [--env <file>] [--exposure <f32>] [--gamma <f32>]"));
+
+    let mut path_trace = false;
+    let mut env_path = None;
+    let mut exposure = DEFAULT_EXPOSURE;
+    let mut gamma = DEFAULT_GAMMA;
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--path-trace" => path_trace = true,
+            "--env" => env_path = Some(args.next()
+                .unwrap_or_else(|| panic!("--env requires a file path argument"))),
+            "--exposure" => exposure = args.next()
+                .unwrap_or_else(|| panic!("--exposure requires a numeric argument"))
+                .parse().expect("bad --exposure value"),
+            "--gamma" => gamma = args.next()
+                .unwrap_or_else(|| panic!("--gamma requires a numeric argument"))
+                .parse().expect("bad --gamma value"),
+            other => panic!("unknown argument '{}'", other),
+        }
+    }
+
+    let mut scene = load_scene(&scene_path);
+    scene.path_trace = path_trace;
+    scene.env = env_path.map(|p| load_environment(&p));
+    let scene = Arc::new(scene);
+
+    // Vectors for orienting camera, derived from the parsed camera record
+    let gv = !(scene.camera.look_at + scene.camera.pos * -1.0);   // Camera direction
+    let scale = (scene.camera.fov.to_radians() * 0.5).tan() / (WIDTH as f32 * 0.5);
+    let av = !(V{x:0.0, y:0.0, z:1.0} ^ gv) * scale;    // Camera up vector, Z is pointing up
+    let bv = !(gv ^ av) * scale;                        // The right vector, obtained via traditional cross-product
     let cv = (av + bv) * -(WIDTH as f32/2.0) + gv;      // Directional offset to create perspective (1/2 width of scene)
 
-    // Tracing progress is written to standard error (one . per row)
+    // Tracing progress is written to standard error (one . per pass)
     eprint!("Tracing ...");
 
-    // Create a thread pool and a channel for thread communication
+    // Create a thread pool, reused across every pass
     let pool = ThreadPool::new(12);
-    let (tx, rx) = channel();
-
-    // Create tasks for all the pixels
-    let pixel_tasks = iproduct!(
-        (1..=WIDTH).rev(), (1..=WIDTH).rev()
-    );
-
-    // Add each task to the thread pool and queue them up to send out their results
-    let mut task_count = 0;
-    for (i, pixel) in pixel_tasks.enumerate() {
-        let tx = tx.clone();
-        pool.execute(move || tx.send(trace_pixel(pixel.1, pixel.0, av, bv, cv, i)).unwrap());
-        task_count += 1;
-    }
 
-    // Receive all the pixel color results collected in a vector, then sort the vector
-    let mut colors = rx.iter().take(task_count).collect::<Vec<(usize, V)>>();
-    colors.sort_by(|a, b| a.0.cmp(&b.0));
+    // Persistent per-pixel accumulation buffer, summed across passes. The
+    // pixel order matches the (y, x) iproduct below, so the task index can
+    // be used to write straight into it without any sort-and-collect.
+    let pixel_count = (WIDTH * WIDTH) as usize;
+    let mut accum = vec![V{x: 0.0, y: 0.0, z: 0.0}; pixel_count];
+
+    // Each pass casts one additional sample per pixel, accumulates it, and
+    // rewrites 'result.ppm' with the running average. This lets the image be
+    // watched as it refines and aborted early once it looks good enough.
+    for pass in 1..=SAMPLES {
+        let (tx, rx) = channel();
+        let pixel_tasks = iproduct!(
+            (1..=WIDTH).rev(), (1..=WIDTH).rev()
+        );
+
+        let mut task_count = 0;
+        for (i, pixel) in pixel_tasks.enumerate() {
+            let tx = tx.clone();
+            let scene = Arc::clone(&scene);
+            pool.execute(move || tx.send((i, sample_pixel(&scene, pixel.1, pixel.0, av, bv, cv))).unwrap());
+            task_count += 1;
+        }
+
+        for (i, color) in rx.iter().take(task_count) {
+            accum[i] = accum[i] + color;
+        }
+
+        // Write the current running average (divide by passes completed so
+        // far), tone-mapped and gamma-encoded for display
+        let mut out = File::create("result.ppm").unwrap();
+        out.write(format!("P6 {} {} 255 ", WIDTH, WIDTH).as_bytes()).unwrap();
+        for color in &accum {
+            out.write(&(*color * (1.0 / pass as f32)).to_srgb_bytes(exposure, gamma)).unwrap();
+        }
 
-    // Write the results to 'result.ppm'
-    let mut out = File::create("result.ppm").unwrap();
-    out.write(format!("P6 {} {} 255 ", WIDTH, WIDTH).as_bytes()).unwrap();
-    for color in colors.iter() {
-        out.write(&color.1.c()).unwrap();
+        eprint!(".");
     }
 
     // Indicate completion